@@ -39,7 +39,8 @@ pub const RESOLVER_LIMIT: Quota =
 pub const SUBBLOCKS_LIMIT: Quota =
     Quota::per_second(NonZeroU32::new(128).expect("value is not zero"));
 
-pub(crate) const NAMESPACE: &[u8] = b"TEMPO";
+/// The namespace under which all consensus messages are signed.
+pub const NAMESPACE: &[u8] = b"TEMPO";
 
 /// The number of peer sets that will be active in the lookup p2p network.
 pub(crate) const PEERSETS_TO_TRACK: usize = 3;
@@ -0,0 +1,711 @@
+//! Global consensus invariants checked alongside deterministic e2e runs.
+//!
+//! The [`InvariantChecker`] subscribes to the consensus feed of every
+//! validator and, on every logical tick, asserts the following properties
+//! across all nodes:
+//!
+//! 1. no two conflicting blocks are finalized at the same height or round;
+//! 2. finalized blocks fall into the epoch they were finalized in, and no node
+//!    finalizes a new block in an epoch older than one it already finalized in;
+//! 3. all nodes agree on the validator set (the DKG outcome) at every epoch
+//!    boundary;
+//! 4. every finalized block carries a valid quorum certificate.
+//!
+//! If any of these is violated, or if the checker misses events or cannot
+//! check a finalization by the end of the run, it dumps the full trace of
+//! observed events as JSON lines and panics. Together with the seed of the
+//! deterministic runtime this is enough to replay and inspect the run.
+
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fmt,
+    io::Write as _,
+    path::PathBuf,
+    time::Duration,
+};
+
+use alloy_primitives::hex;
+use commonware_codec::{Encode as _, ReadExt as _};
+use commonware_consensus::{
+    simplex::{scheme::bls12381_threshold::vrf::Scheme, types::Finalization},
+    types::{Epoch, Epocher as _, FixedEpocher, Height},
+};
+use commonware_cryptography::{
+    bls12381::primitives::{ops::verify_message, variant::MinSig},
+    ed25519::PublicKey,
+};
+use commonware_runtime::{Clock as _, deterministic::Context};
+use commonware_utils::{NZU64, union};
+use itertools::Itertools as _;
+use reth_ethereum::{provider::BlockReader as _, storage::BlockNumReader as _};
+use tempo_commonware_node::{NAMESPACE, consensus::Digest};
+use tempo_dkg_onchain_artifacts::OnchainDkgOutcome;
+use tempo_node::rpc::consensus::{CertifiedBlock, ConsensusFeed as _, Event};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tracing::error;
+
+use crate::TestingNode;
+
+/// The suffix simplex appends to the consensus namespace for finalize votes.
+const FINALIZE_SUFFIX: &[u8] = b"_FINALIZE";
+
+/// How many ticks [`InvariantChecker::finish`] waits for pending
+/// finalizations to become checkable.
+const FINISH_TICKS: u64 = 30;
+
+/// Checks global consensus invariants across all validators of a run.
+pub struct InvariantChecker {
+    /// The seed of the deterministic runtime, recorded for replays.
+    seed: u64,
+    epocher: FixedEpocher,
+    /// The namespace under which finalize votes are signed.
+    finalize_namespace: Vec<u8>,
+    /// The current logical tick.
+    tick: u64,
+    /// Consensus event subscriptions, one per validator.
+    subscriptions: Vec<(String, broadcast::Receiver<Event>)>,
+    /// Per-validator bookkeeping, keyed by validator uid.
+    nodes: BTreeMap<String, NodeState>,
+    /// Every event observed so far, in the order it was observed.
+    trace: Vec<TraceEntry>,
+    /// Finalizations whose height is not yet known.
+    unresolved: Vec<Observation>,
+    /// Finalizations whose certificate was not yet verified, because the
+    /// network identity of their epoch is not yet known.
+    unverified: Vec<Observation>,
+    finalized_by_height: BTreeMap<u64, Observation>,
+    finalized_by_round: BTreeMap<(u64, u64), Observation>,
+    /// The DKG outcome governing an epoch, keyed by epoch.
+    outcomes: BTreeMap<u64, (String, OnchainDkgOutcome)>,
+    finalizations_observed: u64,
+    finalizations_verified: u64,
+    violations: Vec<Violation>,
+}
+
+impl InvariantChecker {
+    /// Creates a checker subscribed to the consensus feeds of `nodes`.
+    ///
+    /// Should be called before the nodes are started so that no events are
+    /// missed. The feed outlives restarts of a node, so the subscriptions
+    /// remain valid for the entire run.
+    pub async fn new(seed: u64, epoch_length: u64, nodes: &[TestingNode<Context>]) -> Self {
+        let mut this = Self {
+            seed,
+            epocher: FixedEpocher::new(NZU64!(epoch_length)),
+            finalize_namespace: union(NAMESPACE, FINALIZE_SUFFIX),
+            tick: 0,
+            subscriptions: vec![],
+            nodes: BTreeMap::new(),
+            trace: vec![],
+            unresolved: vec![],
+            unverified: vec![],
+            finalized_by_height: BTreeMap::new(),
+            finalized_by_round: BTreeMap::new(),
+            outcomes: BTreeMap::new(),
+            finalizations_observed: 0,
+            finalizations_verified: 0,
+            violations: vec![],
+        };
+        for node in nodes {
+            this.subscribe(node).await;
+        }
+        this
+    }
+
+    /// Subscribes to the consensus feed of `node`.
+    ///
+    /// Validators joining the network after the checker was created must be
+    /// subscribed before they are started. Subscribing a node twice is a no-op.
+    pub async fn subscribe(&mut self, node: &TestingNode<Context>) {
+        if self.subscriptions.iter().any(|(uid, _)| uid == node.uid()) {
+            return;
+        }
+        let events = node
+            .consensus_config()
+            .feed_state
+            .subscribe()
+            .await
+            .expect("the feed state handle always supports subscriptions");
+        self.subscriptions.push((node.uid().to_string(), events));
+    }
+
+    /// Advances the checker by one logical tick.
+    ///
+    /// Drains all events emitted since the last tick and checks all
+    /// invariants against them.
+    ///
+    /// # Panics
+    /// Panics if any invariant was violated, after dumping the event trace.
+    pub fn tick(&mut self, nodes: &[TestingNode<Context>]) {
+        self.advance(nodes, true);
+    }
+
+    /// Performs a final tick and waits for all pending finalizations to be
+    /// checked.
+    ///
+    /// Events emitted after the final tick are not considered.
+    ///
+    /// # Panics
+    /// Panics if any invariant was violated, or if a finalization could not be
+    /// checked within [`FINISH_TICKS`] ticks, after dumping the event trace.
+    pub async fn finish(&mut self, context: &Context, nodes: &[TestingNode<Context>]) {
+        self.tick(nodes);
+        for _ in 0..FINISH_TICKS {
+            if self.is_settled() {
+                return;
+            }
+            context.sleep(Duration::from_secs(1)).await;
+            self.advance(nodes, false);
+        }
+        if self.is_settled() {
+            return;
+        }
+
+        for finalization in std::mem::take(&mut self.unresolved) {
+            self.violations.push(Violation::UncheckedFinalization {
+                finalization,
+                reason: "its height was never resolved",
+            });
+        }
+        for finalization in std::mem::take(&mut self.unverified) {
+            self.violations.push(Violation::UncheckedFinalization {
+                finalization,
+                reason: "the network identity of its epoch was never read",
+            });
+        }
+        self.fail();
+    }
+
+    /// Records an event emitted by the node identified by `uid`.
+    ///
+    /// Finalizations are checked for conflicts within their round and for
+    /// epoch regressions right away; all other checks need their height.
+    pub fn observe(&mut self, uid: &str, event: Event) {
+        self.trace.push(TraceEntry {
+            tick: self.tick,
+            node: uid.to_string(),
+            event: event.clone(),
+        });
+        let Event::Finalized { block, .. } = event else {
+            return;
+        };
+        self.finalizations_observed += 1;
+
+        let finalization = Observation {
+            node: uid.to_string(),
+            block,
+        };
+        self.check_epoch_progress(&finalization);
+        self.check_round(&finalization);
+        self.unresolved.push(finalization);
+    }
+
+    /// Records the DKG outcome governing `epoch` as read from the node
+    /// identified by `uid`, checking it against the outcomes of other nodes.
+    pub fn record_outcome(&mut self, uid: &str, epoch: u64, outcome: OnchainDkgOutcome) {
+        match self.outcomes.entry(epoch) {
+            Entry::Vacant(entry) => {
+                entry.insert((uid.to_string(), outcome));
+            }
+            Entry::Occupied(entry) if entry.get().1 != outcome => {
+                self.violations.push(Violation::ValidatorSetMismatch {
+                    epoch,
+                    first: entry.get().clone(),
+                    second: (uid.to_string(), outcome),
+                });
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+
+    /// Checks all invariants against the events observed so far.
+    ///
+    /// Finalizations that cannot be checked yet (because their height or the
+    /// network identity of their epoch is unknown) are retried on the next
+    /// call.
+    pub fn check(&mut self, nodes: &[TestingNode<Context>]) {
+        self.resolve_heights(nodes);
+        self.check_validator_sets(nodes);
+        self.verify_certificates();
+    }
+
+    /// Returns all invariant violations found so far.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Returns how many finalizations were observed across all nodes.
+    pub fn finalizations_observed(&self) -> u64 {
+        self.finalizations_observed
+    }
+
+    /// Returns how many finalizations had their quorum certificate verified.
+    pub fn finalizations_verified(&self) -> u64 {
+        self.finalizations_verified
+    }
+
+    /// Returns whether all observed finalizations were checked.
+    fn is_settled(&self) -> bool {
+        self.unresolved.is_empty() && self.unverified.is_empty()
+    }
+
+    /// Advances the logical clock, optionally drains new events, and checks
+    /// all invariants.
+    fn advance(&mut self, nodes: &[TestingNode<Context>], drain_events: bool) {
+        self.tick += 1;
+        if drain_events {
+            self.drain_events();
+        }
+        self.check(nodes);
+        if !self.violations.is_empty() {
+            self.fail();
+        }
+    }
+
+    fn drain_events(&mut self) {
+        let mut drained = vec![];
+        let mut lagged = vec![];
+        for (uid, events) in &mut self.subscriptions {
+            loop {
+                match events.try_recv() {
+                    Ok(event) => drained.push((uid.clone(), event)),
+                    Err(TryRecvError::Lagged(skipped)) => {
+                        lagged.push(Violation::LaggedFeed {
+                            node: uid.clone(),
+                            skipped,
+                        });
+                    }
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+        }
+        self.violations.extend(lagged);
+        for (uid, event) in drained {
+            self.observe(&uid, event);
+        }
+    }
+
+    /// Checks that a node does not finalize a new block in an epoch older than
+    /// one it already finalized in.
+    ///
+    /// The engine of an exited epoch may still certify re-proposals of the
+    /// epoch's boundary block while the next epoch is starting. A block that
+    /// was already finalized in its epoch is therefore not a regression.
+    fn check_epoch_progress(&mut self, finalization: &Observation) {
+        let epoch = finalization.block.epoch;
+        let refinalized = self
+            .finalized_by_round
+            .range((epoch, 0)..=(epoch, u64::MAX))
+            .any(|(_, other)| other.block.digest == finalization.block.digest);
+
+        let node = self.nodes.entry(finalization.node.clone()).or_default();
+        match node.highest_epoch {
+            Some(highest_epoch) if epoch < highest_epoch && !refinalized => {
+                self.violations.push(Violation::EpochRegression {
+                    highest_epoch,
+                    finalization: finalization.clone(),
+                });
+            }
+            _ => node.highest_epoch = node.highest_epoch.max(Some(epoch)),
+        }
+    }
+
+    /// Checks that no other block was finalized in the same round.
+    fn check_round(&mut self, finalization: &Observation) {
+        match self
+            .finalized_by_round
+            .entry((finalization.block.epoch, finalization.block.view))
+        {
+            Entry::Vacant(entry) => {
+                entry.insert(finalization.clone());
+            }
+            Entry::Occupied(entry) if entry.get().block.digest != finalization.block.digest => {
+                self.violations.push(Violation::ConflictingFinalizations {
+                    first: entry.get().clone(),
+                    second: finalization.clone(),
+                });
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+
+    /// Fills in missing heights from the execution layer and checks the
+    /// height-indexed invariants once a height is known.
+    fn resolve_heights(&mut self, nodes: &[TestingNode<Context>]) {
+        for mut finalization in std::mem::take(&mut self.unresolved) {
+            if finalization.block.height.is_none() {
+                finalization.block.height = resolve_height(nodes, &finalization);
+            }
+            match finalization.block.height {
+                Some(height) => self.check_height(finalization, height),
+                None => self.unresolved.push(finalization),
+            }
+        }
+    }
+
+    /// Checks that a finalization falls into its epoch and does not conflict
+    /// with another finalization at the same height.
+    fn check_height(&mut self, finalization: Observation, height: u64) {
+        let node = self.nodes.entry(finalization.node.clone()).or_default();
+        node.highest_finalized = node.highest_finalized.max(height);
+
+        let expected_epoch = self
+            .epocher
+            .containing(Height::new(height))
+            .map(|info| info.epoch().get());
+        if expected_epoch != Some(finalization.block.epoch) {
+            self.violations.push(Violation::EpochMismatch {
+                finalization: finalization.clone(),
+                expected: expected_epoch,
+            });
+        }
+
+        match self.finalized_by_height.entry(height) {
+            Entry::Vacant(entry) => {
+                entry.insert(finalization.clone());
+            }
+            Entry::Occupied(entry) if entry.get().block.digest != finalization.block.digest => {
+                self.violations.push(Violation::ConflictingFinalizations {
+                    first: entry.get().clone(),
+                    second: finalization.clone(),
+                });
+            }
+            Entry::Occupied(_) => {}
+        }
+
+        self.unverified.push(finalization);
+    }
+
+    /// Reads the DKG outcomes at all finalized epoch boundaries from each
+    /// node's execution layer and checks that all nodes agree on them.
+    fn check_validator_sets(&mut self, nodes: &[TestingNode<Context>]) {
+        for node in nodes {
+            if !node.is_execution_running() {
+                continue;
+            }
+            let uid = node.uid();
+
+            loop {
+                let state = self.nodes.entry(uid.to_string()).or_default();
+                let epoch = state.next_outcome_epoch;
+                let Some(height) = outcome_height(&self.epocher, epoch)
+                    .filter(|height| height.get() <= state.highest_finalized)
+                else {
+                    break;
+                };
+                let Some(outcome) = read_outcome(node, height) else {
+                    // The execution layer has not caught up yet; retry on the next tick.
+                    break;
+                };
+                state.next_outcome_epoch += 1;
+
+                match outcome {
+                    Ok(outcome) => self.record_outcome(uid, epoch, outcome),
+                    Err(reason) => self.violations.push(Violation::MalformedOutcome {
+                        node: uid.to_string(),
+                        epoch,
+                        height: height.get(),
+                        reason,
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Verifies the quorum certificates of all finalizations for which the
+    /// network identity of their epoch is known.
+    fn verify_certificates(&mut self) {
+        for finalization in std::mem::take(&mut self.unverified) {
+            let Some((_, outcome)) = self.outcomes.get(&finalization.block.epoch) else {
+                self.unverified.push(finalization);
+                continue;
+            };
+            match verify_certificate(&finalization.block, outcome, &self.finalize_namespace) {
+                Ok(()) => self.finalizations_verified += 1,
+                Err(reason) => self.violations.push(Violation::MissingQuorumCertificate {
+                    finalization,
+                    reason,
+                }),
+            }
+        }
+    }
+
+    /// Dumps the event trace and panics with all violations found.
+    fn fail(&self) -> ! {
+        for violation in &self.violations {
+            error!(%violation, "consensus invariant violated");
+        }
+
+        let dumped = match self.dump_trace() {
+            Ok(path) => format!("event trace dumped to `{}`", path.display()),
+            Err(err) => format!("failed dumping event trace: {err}"),
+        };
+
+        panic!(
+            "consensus invariants violated at tick {} of run with seed {}; {dumped}\n{}",
+            self.tick,
+            self.seed,
+            self.violations.iter().join("\n"),
+        );
+    }
+
+    /// Writes the event trace as JSON lines to a new file in the temporary
+    /// directory, returning its path.
+    ///
+    /// The file name is unique so that traces of concurrently failing tests
+    /// do not overwrite each other.
+    fn dump_trace(&self) -> std::io::Result<PathBuf> {
+        let (mut file, path) = tempfile::Builder::new()
+            .prefix(&format!("tempo-e2e-invariants-seed-{}-", self.seed))
+            .suffix(".jsonl")
+            .tempfile()?
+            .keep()
+            .map_err(|err| err.error)?;
+        for entry in &self.trace {
+            let line = serde_json::json!({
+                "tick": entry.tick,
+                "node": entry.node,
+                "event": entry.event,
+            });
+            writeln!(file, "{line}")?;
+        }
+        Ok(path)
+    }
+}
+
+/// Bookkeeping for a single validator.
+#[derive(Debug, Default)]
+struct NodeState {
+    /// The highest height finalized by this node.
+    highest_finalized: u64,
+    /// The highest epoch this node finalized a block in.
+    highest_epoch: Option<u64>,
+    /// The next epoch whose governing DKG outcome is read from this node.
+    next_outcome_epoch: u64,
+}
+
+/// An event as observed by the checker.
+struct TraceEntry {
+    tick: u64,
+    node: String,
+    event: Event,
+}
+
+/// A finalization observed at a node.
+#[derive(Clone, Debug)]
+pub struct Observation {
+    pub node: String,
+    pub block: CertifiedBlock,
+}
+
+impl fmt::Display for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` finalized `{}` at height ",
+            self.node, self.block.digest
+        )?;
+        match self.block.height {
+            Some(height) => write!(f, "{height}")?,
+            None => write!(f, "<unknown>")?,
+        }
+        write!(f, " (epoch {}, view {})", self.block.epoch, self.block.view)
+    }
+}
+
+/// A violated consensus invariant.
+#[derive(Debug)]
+pub enum Violation {
+    /// Two different blocks were finalized at the same height or round.
+    ConflictingFinalizations {
+        first: Observation,
+        second: Observation,
+    },
+    /// A block was finalized in an epoch other than the one its height falls into.
+    EpochMismatch {
+        finalization: Observation,
+        expected: Option<u64>,
+    },
+    /// A node finalized a new block in an epoch older than one it already
+    /// finalized in.
+    EpochRegression {
+        highest_epoch: u64,
+        finalization: Observation,
+    },
+    /// Two nodes disagree on the DKG outcome governing an epoch.
+    ValidatorSetMismatch {
+        epoch: u64,
+        first: (String, OnchainDkgOutcome),
+        second: (String, OnchainDkgOutcome),
+    },
+    /// A finalized epoch boundary block does not carry a valid DKG outcome.
+    MalformedOutcome {
+        node: String,
+        epoch: u64,
+        height: u64,
+        reason: String,
+    },
+    /// A finalized block does not carry a valid quorum certificate.
+    MissingQuorumCertificate {
+        finalization: Observation,
+        reason: String,
+    },
+    /// The checker missed events emitted by a node.
+    LaggedFeed { node: String, skipped: u64 },
+    /// A finalization could not be checked by the end of the run.
+    UncheckedFinalization {
+        finalization: Observation,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConflictingFinalizations { first, second } => {
+                write!(f, "conflicting finalizations: {first}, but {second}")
+            }
+            Self::EpochMismatch {
+                finalization,
+                expected,
+            } => write!(
+                f,
+                "epoch mismatch: {finalization}, but the height falls into epoch {expected:?}"
+            ),
+            Self::EpochRegression {
+                highest_epoch,
+                finalization,
+            } => write!(
+                f,
+                "epoch regression: {finalization}, but it already finalized in epoch \
+                {highest_epoch}"
+            ),
+            Self::ValidatorSetMismatch {
+                epoch,
+                first: (first_node, first),
+                second: (second_node, second),
+            } => write!(
+                f,
+                "validator set mismatch for epoch {epoch}: `{first_node}` has players \
+                {:?} and next players {:?}, but `{second_node}` has players {:?} and next \
+                players {:?}",
+                first.players(),
+                first.next_players(),
+                second.players(),
+                second.next_players(),
+            ),
+            Self::MalformedOutcome {
+                node,
+                epoch,
+                height,
+                reason,
+            } => write!(
+                f,
+                "malformed DKG outcome: the block at height {height} of `{node}` governing \
+                epoch {epoch} {reason}"
+            ),
+            Self::MissingQuorumCertificate {
+                finalization,
+                reason,
+            } => write!(
+                f,
+                "missing quorum certificate: {finalization}, but {reason}"
+            ),
+            Self::LaggedFeed { node, skipped } => write!(
+                f,
+                "lagged consensus feed: the checker missed {skipped} events of `{node}`"
+            ),
+            Self::UncheckedFinalization {
+                finalization,
+                reason,
+            } => write!(f, "unchecked finalization: {finalization}, but {reason}"),
+        }
+    }
+}
+
+/// Returns the height of the block containing the DKG outcome that governs
+/// `epoch`.
+///
+/// Epoch 0 is governed by the outcome in the genesis block, every other epoch
+/// by the outcome in the last block of its preceding epoch.
+fn outcome_height(epocher: &FixedEpocher, epoch: u64) -> Option<Height> {
+    match epoch.checked_sub(1) {
+        None => Some(Height::new(0)),
+        Some(previous) => epocher.last(Epoch::new(previous)),
+    }
+}
+
+/// Looks up the height of a finalized block in the execution layer.
+///
+/// The node that emitted the finalization is asked first. Any other running
+/// node is used as a fallback, for example if the emitting node was stopped.
+fn resolve_height(nodes: &[TestingNode<Context>], finalization: &Observation) -> Option<u64> {
+    nodes
+        .iter()
+        .filter(|node| node.is_execution_running())
+        .sorted_by_key(|node| node.uid() != finalization.node)
+        .find_map(|node| {
+            node.execution_provider()
+                .block_number(finalization.block.digest)
+                .ok()
+                .flatten()
+        })
+}
+
+/// Reads the DKG outcome from the block at `height` of a node's execution layer.
+///
+/// Returns `None` if the block is not yet known to the execution layer, and
+/// an error if the block does not carry a valid DKG outcome.
+pub(crate) fn read_outcome(
+    node: &TestingNode<Context>,
+    height: Height,
+) -> Option<Result<OnchainDkgOutcome, String>> {
+    let block = node
+        .execution_provider()
+        .block_by_number(height.get())
+        .ok()??;
+    let extra_data = &block.header.inner.extra_data;
+    if extra_data.is_empty() {
+        return Some(Err("carries no DKG outcome".to_string()));
+    }
+    Some(
+        OnchainDkgOutcome::read(&mut extra_data.as_ref())
+            .map_err(|err| format!("carries an undecodable DKG outcome: {err}")),
+    )
+}
+
+/// Verifies that the certificate of `block` is a threshold signature by the
+/// network identity set in `outcome` over the finalized proposal.
+fn verify_certificate(
+    block: &CertifiedBlock,
+    outcome: &OnchainDkgOutcome,
+    namespace: &[u8],
+) -> Result<(), String> {
+    let bytes = hex::decode(&block.certificate)
+        .map_err(|err| format!("the certificate is not valid hex: {err}"))?;
+    let certificate =
+        Finalization::<Scheme<PublicKey, MinSig>, Digest>::read(&mut bytes.as_slice())
+            .map_err(|err| format!("the certificate could not be decoded: {err}"))?;
+
+    if certificate.proposal.payload.0 != block.digest {
+        return Err(format!(
+            "the certificate is for digest `{}`",
+            certificate.proposal.payload.0
+        ));
+    }
+    let (epoch, view) = (
+        certificate.proposal.round.epoch().get(),
+        certificate.proposal.round.view().get(),
+    );
+    if (epoch, view) != (block.epoch, block.view) {
+        return Err(format!("the certificate is for epoch {epoch}, view {view}"));
+    }
+
+    verify_message::<MinSig>(
+        outcome.sharing().public(),
+        namespace,
+        &certificate.proposal.encode(),
+        &certificate.certificate.vote_signature,
+    )
+    .map_err(|err| format!("the certificate signature is invalid: {err:?}"))
+}
@@ -34,6 +34,8 @@ use tempo_commonware_node::{consensus, feed::FeedStateHandle};
 
 pub mod execution_runtime;
 pub use execution_runtime::ExecutionNodeConfig;
+pub mod invariants;
+pub use invariants::InvariantChecker;
 pub mod testing_node;
 pub use execution_runtime::ExecutionRuntime;
 use tempo_dkg_onchain_artifacts::OnchainDkgOutcome;
@@ -277,17 +279,33 @@ pub async fn setup_validators(
 }
 
 /// Runs a test configured by [`Setup`].
-pub fn run(setup: Setup, mut stop_condition: impl FnMut(&str, &str) -> bool) -> String {
+///
+/// Consensus invariants are checked by an [`InvariantChecker`] on every tick
+/// of the run.
+pub fn run(setup: Setup, stop_condition: impl FnMut(&str, &str) -> bool) -> String {
+    run_with_invariants(setup, stop_condition).0
+}
+
+/// Like [`run`], but also returns the [`InvariantChecker`] after it finished.
+pub fn run_with_invariants(
+    setup: Setup,
+    mut stop_condition: impl FnMut(&str, &str) -> bool,
+) -> (String, InvariantChecker) {
     let cfg = deterministic::Config::default().with_seed(setup.seed);
     let executor = Runner::from(cfg);
 
     executor.start(|mut context| async move {
+        let (seed, epoch_length) = (setup.seed, setup.epoch_length);
+
         // Setup and run all validators.
         let (mut nodes, _execution_runtime) = setup_validators(&mut context, setup).await;
+        let mut invariants = InvariantChecker::new(seed, epoch_length, &nodes).await;
 
         join_all(nodes.iter_mut().map(|node| node.start(&context))).await;
 
         loop {
+            invariants.tick(&nodes);
+
             let metrics = context.encode();
 
             let mut success = false;
@@ -318,7 +336,9 @@ pub fn run(setup: Setup, mut stop_condition: impl FnMut(&str, &str) -> bool) ->
             context.sleep(Duration::from_secs(1)).await;
         }
 
-        context.auditor().state()
+        invariants.finish(&context, &nodes).await;
+
+        (context.auditor().state(), invariants)
     })
 }
 
@@ -2,29 +2,23 @@
 
 use std::time::Duration;
 
-use commonware_codec::ReadExt as _;
 use commonware_consensus::types::{Epoch, Epocher as _, FixedEpocher, Height};
 use commonware_runtime::{Clock as _, Metrics as _, deterministic::Context};
 use commonware_utils::NZU64;
-use reth_ethereum::provider::BlockReader as _;
 use tempo_dkg_onchain_artifacts::OnchainDkgOutcome;
 
-use crate::{CONSENSUS_NODE_PREFIX, TestingNode};
+use crate::{CONSENSUS_NODE_PREFIX, TestingNode, invariants::read_outcome};
 
-/// Reads the DKG outcome from a block, returns None if block doesn't exist or has no outcome.
+/// Reads the DKG outcome from a block, returns None if the block doesn't exist.
+///
+/// # Panics
+/// Panics if the block does not carry a valid DKG outcome.
 pub(crate) fn read_outcome_from_validator(
     validator: &TestingNode<Context>,
     block_num: Height,
 ) -> Option<OnchainDkgOutcome> {
-    let provider = validator.execution_provider();
-    let block = provider.block_by_number(block_num.get()).ok()??;
-    let extra_data = &block.header.inner.extra_data;
-
-    if extra_data.is_empty() {
-        return None;
-    }
-
-    Some(OnchainDkgOutcome::read(&mut extra_data.as_ref()).expect("valid DKG outcome"))
+    let outcome = read_outcome(validator, block_num)?;
+    Some(outcome.unwrap_or_else(|reason| panic!("block {block_num} {reason}")))
 }
 
 /// Parses a metric line, returning (metric_name, value) if valid.
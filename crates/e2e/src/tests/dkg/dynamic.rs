@@ -8,7 +8,7 @@ use commonware_runtime::{
 };
 use futures::future::join_all;
 
-use crate::{CONSENSUS_NODE_PREFIX, Setup, setup_validators};
+use crate::{CONSENSUS_NODE_PREFIX, InvariantChecker, Setup, setup_validators};
 
 #[test_traced]
 fn validator_is_added_to_a_set_of_three() {
@@ -55,11 +55,14 @@ impl AssertValidatorIsAdded {
             .how_many_verifiers(1)
             .epoch_length(epoch_length);
 
-        let cfg = Config::default().with_seed(setup.seed);
+        let (seed, epoch_length) = (setup.seed, setup.epoch_length);
+
+        let cfg = Config::default().with_seed(seed);
         let executor = Runner::from(cfg);
 
         executor.start(|mut context| async move {
             let (mut validators, execution_runtime) = setup_validators(&mut context, setup).await;
+            let mut invariants = InvariantChecker::new(seed, epoch_length, &validators).await;
 
             let mut new_validator = {
                 let idx = validators
@@ -104,13 +107,15 @@ impl AssertValidatorIsAdded {
                 "addValidator call returned receipt"
             );
 
-            let _new_validator = new_validator.start(&context).await;
+            invariants.subscribe(&new_validator).await;
+            new_validator.start(&context).await;
             tracing::info!("new validator was started");
 
             // First, all initial validator nodes must observe a ceremony with
             // dealers = how_many_initial, players = how_many_initial + 1.
             loop {
                 context.sleep(Duration::from_secs(1)).await;
+                invariants.tick(&validators);
 
                 let mut dealers_is_initial = 0;
                 let mut players_is_initial_plus_one = 0;
@@ -159,6 +164,7 @@ impl AssertValidatorIsAdded {
             // same number of participants (= how_many_initial + 1).
             loop {
                 context.sleep(Duration::from_secs(1)).await;
+                invariants.tick(&validators);
 
                 let metrics = context.encode();
                 let mut participants_is_initial_plus_one = 0;
@@ -181,6 +187,9 @@ impl AssertValidatorIsAdded {
                     break;
                 }
             }
+
+            validators.push(new_validator);
+            invariants.finish(&context, &validators).await;
         })
     }
 }
@@ -202,11 +211,14 @@ impl AssertValidatorIsRemoved {
             .how_many_signers(how_many_initial)
             .epoch_length(epoch_length);
 
-        let cfg = Config::default().with_seed(setup.seed);
+        let (seed, epoch_length) = (setup.seed, setup.epoch_length);
+
+        let cfg = Config::default().with_seed(seed);
         let executor = Runner::from(cfg);
 
         executor.start(|mut context| async move {
             let (mut validators, execution_runtime) = setup_validators(&mut context, setup).await;
+            let mut invariants = InvariantChecker::new(seed, epoch_length, &validators).await;
 
             join_all(validators.iter_mut().map(|v| v.start(&context))).await;
 
@@ -241,6 +253,7 @@ impl AssertValidatorIsRemoved {
             // original dealer set.
             loop {
                 context.sleep(Duration::from_secs(1)).await;
+                invariants.tick(&validators);
 
                 let mut dealers_is_initial = 0;
                 let mut players_is_initial_minus_one = 0;
@@ -290,6 +303,7 @@ impl AssertValidatorIsRemoved {
             // includes the validator to be removed, since it will still transition.
             loop {
                 context.sleep(Duration::from_secs(1)).await;
+                invariants.tick(&validators);
 
                 let metrics = context.encode();
                 let mut participants_is_initial_minus_one = 0;
@@ -312,6 +326,8 @@ impl AssertValidatorIsRemoved {
                     break;
                 }
             }
+
+            invariants.finish(&context, &validators).await;
         })
     }
 }
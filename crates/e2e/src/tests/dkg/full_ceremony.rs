@@ -9,7 +9,7 @@ use commonware_runtime::{
 use futures::future::join_all;
 
 use super::common::{assert_no_dkg_failures, wait_for_epoch, wait_for_outcome};
-use crate::{Setup, setup_validators};
+use crate::{InvariantChecker, Setup, setup_validators};
 
 #[test_traced]
 fn full_dkg_ceremony() {
@@ -34,12 +34,14 @@ impl FullDkgTest {
         let setup = Setup::new()
             .how_many_signers(self.how_many_signers)
             .epoch_length(self.epoch_length);
+        let seed = setup.seed;
 
-        let cfg = Config::default().with_seed(setup.seed);
+        let cfg = Config::default().with_seed(seed);
         let executor = Runner::from(cfg);
 
         executor.start(|mut context| async move {
             let (mut validators, execution_runtime) = setup_validators(&mut context, setup).await;
+            let mut invariants = InvariantChecker::new(seed, self.epoch_length, &validators).await;
 
             join_all(validators.iter_mut().map(|v| v.start(&context))).await;
 
@@ -58,6 +60,7 @@ impl FullDkgTest {
                 .unwrap();
 
             tracing::info!(full_dkg_epoch = self.full_dkg_epoch, "Scheduled full DKG");
+            invariants.tick(&validators);
 
             // Step 1: Wait for and verify the is_next_full_dkg flag in epoch N-1
             let outcome_before = wait_for_outcome(
@@ -75,6 +78,7 @@ impl FullDkgTest {
             );
             let pubkey_before = *outcome_before.sharing().public();
             tracing::info!(?pubkey_before, "Group public key BEFORE full DKG");
+            invariants.tick(&validators);
 
             // Step 2: Wait for full DKG to complete (epoch N+1)
             wait_for_epoch(&context, self.full_dkg_epoch + 1, self.how_many_signers).await;
@@ -97,6 +101,7 @@ impl FullDkgTest {
                 "Full DKG must produce a DIFFERENT group public key"
             );
             tracing::info!("Verified: full DKG created independent polynomial");
+            invariants.tick(&validators);
 
             // Step 4: Wait for reshare (epoch N+2) and verify it PRESERVES the public key
             wait_for_epoch(&context, self.full_dkg_epoch + 2, self.how_many_signers).await;
@@ -124,6 +129,10 @@ impl FullDkgTest {
                 "Reshare must PRESERVE the group public key"
             );
             tracing::info!("Verified: reshare preserved polynomial (full DKG only ran once)");
+
+            // The certificates of epochs after the full DKG must verify against
+            // the new network identity.
+            invariants.finish(&context, &validators).await;
         })
     }
 }
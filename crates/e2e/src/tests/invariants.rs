@@ -0,0 +1,325 @@
+//! Tests for the consensus invariant checker.
+
+use std::{iter::repeat_with, panic::AssertUnwindSafe, time::Duration};
+
+use alloy_primitives::B256;
+use commonware_consensus::types::{Epoch, Height};
+use commonware_cryptography::{
+    Signer as _,
+    bls12381::{dkg, primitives::sharing::Mode},
+    ed25519::PrivateKey,
+};
+use commonware_macros::test_traced;
+use commonware_math::algebra::Random as _;
+use commonware_runtime::{
+    Clock as _, Runner as _,
+    deterministic::{Config, Context, Runner},
+};
+use commonware_utils::{N3f1, TryFromIterator as _, ordered};
+use tempo_dkg_onchain_artifacts::OnchainDkgOutcome;
+use tempo_node::rpc::consensus::{CertifiedBlock, ConsensusFeed as _, Event};
+use tokio::sync::broadcast::error::TryRecvError;
+
+use super::dkg::common::read_outcome_from_validator;
+use crate::{
+    InvariantChecker, Setup, invariants::Violation, run_with_invariants, setup_validators,
+};
+
+#[test_traced]
+fn invariants_hold_across_epochs() {
+    let _ = tempo_eyre::install();
+
+    let setup = Setup::new().how_many_signers(4).epoch_length(10);
+
+    let (_, invariants) = run_with_invariants(setup, |metric, value| {
+        metric.ends_with("_epoch_manager_latest_epoch") && value.parse::<u64>().unwrap() >= 3
+    });
+
+    assert!(
+        invariants.finalizations_observed() > 0,
+        "finalizations must have been observed"
+    );
+    assert_eq!(
+        invariants.finalizations_observed(),
+        invariants.finalizations_verified(),
+        "the quorum certificates of all observed finalizations must have been verified"
+    );
+}
+
+#[test_traced]
+fn tampered_finalizations_are_detected() {
+    let _ = tempo_eyre::install();
+
+    let setup = Setup::new().how_many_signers(1).epoch_length(100);
+    let seed = setup.seed;
+
+    let executor = Runner::from(Config::default().with_seed(seed));
+    executor.start(|mut context| async move {
+        let (mut validators, _execution_runtime) = setup_validators(&mut context, setup).await;
+        let uid = validators[0].uid().to_string();
+        let mut events = validators[0]
+            .consensus_config()
+            .feed_state
+            .subscribe()
+            .await
+            .unwrap();
+
+        validators[0].start(&context).await;
+
+        let block = loop {
+            match events.try_recv() {
+                Ok(Event::Finalized { block, .. }) if block.height.is_some() => break block,
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty) => context.sleep(Duration::from_secs(1)).await,
+                Err(TryRecvError::Closed) => panic!("the consensus feed closed before finalizing"),
+            }
+        };
+        let genesis = read_outcome_from_validator(&validators[0], Height::new(0))
+            .expect("genesis carries the initial DKG outcome");
+        let foreign = deal_outcome(&mut context, 1);
+
+        // The untampered finalization verifies against the network identity.
+        let mut invariants = InvariantChecker::new(seed, 100, &[]).await;
+        invariants.record_outcome(&uid, 0, genesis.clone());
+        invariants.observe(&uid, finalized(block.clone()));
+        invariants.check(&[]);
+        assert!(
+            invariants.violations().is_empty(),
+            "{:?}",
+            invariants.violations()
+        );
+        assert_eq!(invariants.finalizations_verified(), 1);
+
+        // The certificate does not cover a different digest.
+        let mut invariants = InvariantChecker::new(seed, 100, &[]).await;
+        invariants.record_outcome(&uid, 0, genesis);
+        invariants.observe(
+            &uid,
+            finalized(CertifiedBlock {
+                digest: B256::repeat_byte(0xff),
+                ..block.clone()
+            }),
+        );
+        invariants.check(&[]);
+        assert!(
+            matches!(
+                invariants.violations(),
+                [Violation::MissingQuorumCertificate { .. }]
+            ),
+            "{:?}",
+            invariants.violations()
+        );
+
+        // The certificate is not signed by a different network identity.
+        let mut invariants = InvariantChecker::new(seed, 100, &[]).await;
+        invariants.record_outcome(&uid, 0, foreign);
+        invariants.observe(&uid, finalized(block));
+        invariants.check(&[]);
+        assert!(
+            matches!(
+                invariants.violations(),
+                [Violation::MissingQuorumCertificate { .. }]
+            ),
+            "{:?}",
+            invariants.violations()
+        );
+    });
+}
+
+#[test]
+fn diverging_validator_sets_are_detected() {
+    let executor = Runner::from(Config::default().with_seed(0));
+    executor.start(|mut context| async move {
+        let first = deal_outcome(&mut context, 4);
+        let second = deal_outcome(&mut context, 4);
+
+        let mut invariants = InvariantChecker::new(0, 10, &[]).await;
+        invariants.record_outcome("a", 1, first.clone());
+        invariants.record_outcome("b", 1, first);
+        assert!(invariants.violations().is_empty());
+
+        invariants.record_outcome("c", 1, second);
+        assert!(
+            matches!(
+                invariants.violations(),
+                [Violation::ValidatorSetMismatch { epoch: 1, first, second }]
+                    if first.0 == "a" && second.0 == "c"
+            ),
+            "{:?}",
+            invariants.violations(),
+        );
+    });
+}
+
+#[test]
+fn conflicting_finalizations_are_detected() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", synthetic(0, 3, 3, B256::repeat_byte(1)));
+    invariants.observe("b", synthetic(0, 4, 3, B256::repeat_byte(2)));
+    invariants.check(&[]);
+
+    assert!(
+        matches!(
+            invariants.violations(),
+            [Violation::ConflictingFinalizations { first, second }]
+                if first.node == "a" && second.node == "b"
+        ),
+        "{:?}",
+        invariants.violations(),
+    );
+}
+
+#[test]
+fn conflicting_rounds_are_detected_without_heights() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", unresolved(0, 3, B256::repeat_byte(1)));
+    invariants.observe("b", unresolved(0, 3, B256::repeat_byte(2)));
+
+    assert!(
+        matches!(
+            invariants.violations(),
+            [Violation::ConflictingFinalizations { first, second }]
+                if first.node == "a" && second.node == "b"
+        ),
+        "{:?}",
+        invariants.violations(),
+    );
+}
+
+#[test]
+fn finalizations_in_wrong_epoch_are_detected() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", synthetic(0, 13, 16, B256::repeat_byte(1)));
+    invariants.check(&[]);
+
+    assert!(
+        matches!(
+            invariants.violations(),
+            [Violation::EpochMismatch {
+                expected: Some(1),
+                ..
+            }]
+        ),
+        "{:?}",
+        invariants.violations(),
+    );
+}
+
+#[test]
+fn epoch_regressions_are_detected() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", synthetic(1, 12, 15, B256::repeat_byte(1)));
+    invariants.observe("b", synthetic(0, 5, 5, B256::repeat_byte(2)));
+    assert!(
+        invariants.violations().is_empty(),
+        "{:?}",
+        invariants.violations()
+    );
+
+    invariants.observe("a", synthetic(0, 5, 5, B256::repeat_byte(2)));
+    invariants.check(&[]);
+
+    assert!(
+        matches!(
+            invariants.violations(),
+            [Violation::EpochRegression {
+                highest_epoch: 1,
+                finalization,
+            }] if finalization.node == "a"
+        ),
+        "{:?}",
+        invariants.violations(),
+    );
+}
+
+#[test]
+fn boundary_blocks_may_be_refinalized_in_the_old_epoch() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", synthetic(0, 9, 9, B256::repeat_byte(1)));
+    invariants.observe("a", synthetic(1, 1, 10, B256::repeat_byte(2)));
+    invariants.observe("a", synthetic(0, 11, 9, B256::repeat_byte(1)));
+    invariants.check(&[]);
+
+    assert!(
+        invariants.violations().is_empty(),
+        "{:?}",
+        invariants.violations()
+    );
+}
+
+#[test]
+fn violations_dump_the_event_trace() {
+    let mut invariants = futures::executor::block_on(InvariantChecker::new(0, 10, &[]));
+
+    invariants.observe("a", synthetic(0, 3, 3, B256::repeat_byte(1)));
+    invariants.observe("b", synthetic(0, 4, 3, B256::repeat_byte(2)));
+
+    let panic = std::panic::catch_unwind(AssertUnwindSafe(|| invariants.tick(&[])))
+        .expect_err("a violation must fail the run");
+    let message = panic
+        .downcast_ref::<String>()
+        .expect("the panic message is formatted");
+    let path = message
+        .split("event trace dumped to `")
+        .nth(1)
+        .and_then(|rest| rest.split('`').next())
+        .unwrap_or_else(|| panic!("the panic message names the trace file: {message}"));
+
+    let trace = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let entries = trace
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 2);
+    for (entry, node) in entries.iter().zip(["a", "b"]) {
+        assert_eq!(entry["node"], node);
+        assert_eq!(entry["event"]["type"], "finalized");
+        assert_eq!(entry["event"]["height"], 3);
+    }
+}
+
+fn finalized(block: CertifiedBlock) -> Event {
+    Event::Finalized { block, seen: 0 }
+}
+
+fn synthetic(epoch: u64, view: u64, height: u64, digest: B256) -> Event {
+    certified(epoch, view, Some(height), digest)
+}
+
+fn unresolved(epoch: u64, view: u64, digest: B256) -> Event {
+    certified(epoch, view, None, digest)
+}
+
+fn certified(epoch: u64, view: u64, height: Option<u64>, digest: B256) -> Event {
+    finalized(CertifiedBlock {
+        epoch,
+        view,
+        height,
+        digest,
+        certificate: String::new(),
+    })
+}
+
+/// Deals a fresh DKG outcome for `how_many` random players.
+fn deal_outcome(context: &mut Context, how_many: usize) -> OnchainDkgOutcome {
+    let players = ordered::Set::try_from_iter(
+        repeat_with(|| PrivateKey::random(&mut *context).public_key()).take(how_many),
+    )
+    .unwrap();
+    let (output, shares) =
+        dkg::deal::<_, _, N3f1>(&mut *context, Mode::NonZeroCounter, players).unwrap();
+
+    OnchainDkgOutcome {
+        epoch: Epoch::zero(),
+        output,
+        next_players: shares.keys().clone(),
+        is_next_full_dkg: false,
+    }
+}
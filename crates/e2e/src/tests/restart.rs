@@ -16,7 +16,7 @@ use futures::future::join_all;
 use rand::Rng;
 use tracing::debug;
 
-use crate::{CONSENSUS_NODE_PREFIX, Setup, setup_validators};
+use crate::{CONSENSUS_NODE_PREFIX, InvariantChecker, Setup, setup_validators};
 
 /// Test configuration for restart scenarios
 #[derive(Clone)]
@@ -52,6 +52,8 @@ fn run_restart_test(
     executor.start(|mut context| async move {
         let (mut validators, _execution_runtime) =
             setup_validators(&mut context, node_setup.clone()).await;
+        let mut invariants =
+            InvariantChecker::new(node_setup.seed, node_setup.epoch_length, &validators).await;
 
         join_all(validators.iter_mut().map(|v| v.start(&context))).await;
 
@@ -64,6 +66,7 @@ fn run_restart_test(
             node_setup.how_many_signers,
             shutdown_height,
             false,
+            || invariants.tick(&validators),
         )
         .await;
 
@@ -82,6 +85,7 @@ fn run_restart_test(
             node_setup.how_many_signers - 1,
             restart_height,
             false,
+            || invariants.tick(&validators),
         )
         .await;
 
@@ -101,22 +105,30 @@ fn run_restart_test(
             node_setup.how_many_signers,
             final_height,
             assert_skips,
+            || invariants.tick(&validators),
         )
         .await;
 
+        invariants.finish(&context, &validators).await;
+
         context.auditor().state()
     })
 }
 
 /// Wait for a specific number of validators to reach a target height
+///
+/// `on_tick` is called once per polling iteration.
 async fn wait_for_height(
     context: &Context,
     expected_validators: u32,
     target_height: u64,
     assert_skips: bool,
+    mut on_tick: impl FnMut(),
 ) {
     let mut skips_observed = false;
     loop {
+        on_tick();
+
         let metrics = context.encode();
         let mut validators_at_height = 0;
 
@@ -228,7 +240,14 @@ fn network_resumes_after_restart_with_el_p2p() {
                 height = shutdown_height,
                 "waiting for network to reach target height before stopping a validator",
             );
-            wait_for_height(&context, setup.how_many_signers, shutdown_height, false).await;
+            wait_for_height(
+                &context,
+                setup.how_many_signers,
+                shutdown_height,
+                false,
+                || {},
+            )
+            .await;
 
             let idx = context.gen_range(0..validators.len());
             validators[idx].stop().await;
@@ -248,7 +267,14 @@ fn network_resumes_after_restart_with_el_p2p() {
                 height = final_height,
                 "waiting for reconstituted validators to reach target height to reach test success",
             );
-            wait_for_height(&context, validators.len() as u32, final_height, false).await;
+            wait_for_height(
+                &context,
+                validators.len() as u32,
+                final_height,
+                false,
+                || {},
+            )
+            .await;
         })
     }
 }
@@ -282,7 +308,14 @@ fn network_resumes_after_restart_without_el_p2p() {
                 height = shutdown_height,
                 "waiting for network to reach target height before stopping a validator",
             );
-            wait_for_height(&context, setup.how_many_signers, shutdown_height, false).await;
+            wait_for_height(
+                &context,
+                setup.how_many_signers,
+                shutdown_height,
+                false,
+                || {},
+            )
+            .await;
 
             let idx = context.gen_range(0..validators.len());
             validators[idx].stop().await;
@@ -302,7 +335,14 @@ fn network_resumes_after_restart_without_el_p2p() {
                 height = final_height,
                 "waiting for reconstituted validators to reach target height to reach test success",
             );
-            wait_for_height(&context, validators.len() as u32, final_height, false).await;
+            wait_for_height(
+                &context,
+                validators.len() as u32,
+                final_height,
+                false,
+                || {},
+            )
+            .await;
         })
     }
 }